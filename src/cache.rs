@@ -0,0 +1,94 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use sha2::Digest;
+use sha2::Sha256;
+use tracing::debug;
+use tracing::instrument;
+
+/// On-disk cache of encoded outputs, keyed by the source's identity (its
+/// storage key together with a cheap stat/ETag-style description of its
+/// current content) and the transform that produced the output. Entries
+/// are evicted least-recently-used once the directory grows past
+/// `max_bytes`.
+#[derive(Debug)]
+pub(crate) struct Cache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl Cache {
+    pub(crate) fn new(dir: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, max_bytes })
+    }
+
+    /// Hashes the source's storage key and cheap stat info together with a
+    /// caller-supplied description of the transform into a stable cache
+    /// key, without needing the full source bytes on hand.
+    pub(crate) fn key(source_key: &str, source_stat: &str, transform: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(source_key.as_bytes());
+        hasher.update(source_stat.as_bytes());
+        hasher.update(transform.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    #[instrument(skip(self))]
+    pub(crate) fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let path = self.path_for(key);
+        let bytes = fs::read(&path).ok()?;
+        // Bump mtime so the LRU eviction below treats this as recently used.
+        if let Err(error) = fs::File::open(&path).and_then(|file| file.set_modified(SystemTime::now())) {
+            debug!(%error, "failed to bump cache entry mtime");
+        }
+        debug!(key, bytes = bytes.len(), "cache hit");
+        Some(bytes)
+    }
+
+    #[instrument(skip(self, bytes))]
+    pub(crate) fn put(&self, key: &str, bytes: &[u8]) {
+        let path = self.path_for(key);
+        if let Err(error) = fs::write(&path, bytes) {
+            debug!(%error, "failed to write cache entry");
+            return;
+        }
+        if let Err(error) = self.evict() {
+            debug!(%error, "failed to evict cache entries");
+        }
+    }
+
+    /// Removes least-recently-used entries until the directory is back
+    /// under `max_bytes`.
+    fn evict(&self) -> io::Result<()> {
+        let mut entries = fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let modified = metadata.modified().ok()?;
+                Some((entry.path(), metadata.len(), modified))
+            })
+            .collect::<Vec<_>>();
+
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        for (path, len, _) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(&path)?;
+            total = total.saturating_sub(len);
+        }
+        Ok(())
+    }
+}
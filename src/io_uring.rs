@@ -0,0 +1,88 @@
+//! Optional io_uring-backed file reads (Linux only), so a source-file read
+//! doesn't tie up a blocking-pool thread the way `tokio::fs::read` does
+//! under the hood. Gated behind the `io-uring` Cargo feature; falls back
+//! to a regular async read when the feature is off or the flag is
+//! disabled. Kernel support is not probed: with the feature built in and
+//! the flag set, the server starts tokio-uring's runtime unconditionally,
+//! and that start fails on a kernel without io_uring.
+
+use std::path::Path;
+
+use bytes::Bytes;
+
+use crate::Error;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring {
+    use std::path::Path;
+
+    use bytes::Bytes;
+
+    use crate::Error;
+
+    /// `None` means "couldn't use io_uring here, fall back"; it is not an
+    /// error in itself.
+    pub(crate) async fn read(path: &Path) -> Option<Result<Bytes, Error>> {
+        let file = tokio_uring::fs::File::open(path).await.ok()?;
+        let metadata = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            Err(error) => return Some(Err(error.into())),
+        };
+        #[allow(clippy::cast_possible_truncation)]
+        let len = metadata.len() as usize;
+        let mut buf = Vec::with_capacity(len);
+        // A single `read_at` isn't guaranteed to fill the buffer even for a
+        // regular file, so keep reading at the current offset until we've
+        // got the whole file or hit EOF/an error, the way `std`/
+        // `tokio::fs::read` do internally.
+        while buf.len() < len {
+            let remaining = len - buf.len();
+            let offset = buf.len() as u64;
+            let (result, chunk) = file.read_at(Vec::with_capacity(remaining), offset).await;
+            let read = match result {
+                Ok(read) => read,
+                Err(error) => {
+                    let _ = file.close().await;
+                    return Some(Err(error.into()));
+                }
+            };
+            if read == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..read]);
+        }
+        let _ = file.close().await;
+        if buf.len() != len {
+            return Some(Err(Error::FailedToResize {
+                message: format!("short read: expected {len} bytes, got {}", buf.len()),
+            }));
+        }
+        Some(Ok(Bytes::from(buf)))
+    }
+}
+
+#[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+mod uring {
+    use std::path::Path;
+
+    use bytes::Bytes;
+
+    use crate::Error;
+
+    pub(crate) async fn read(_path: &Path) -> Option<Result<Bytes, Error>> {
+        None
+    }
+}
+
+/// Reads `path` via io_uring when `enabled` and this build has the
+/// `io-uring` feature on Linux (and the io_uring open succeeds), otherwise
+/// falls back to a regular async read.
+pub(crate) async fn read(path: &Path, enabled: bool) -> Result<Bytes, Error> {
+    if enabled {
+        if let Some(result) = uring::read(path).await {
+            return result;
+        }
+    }
+    let bytes = tokio::fs::read(path).await?;
+    Ok(Bytes::from(bytes))
+}
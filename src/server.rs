@@ -1,17 +1,22 @@
 use std::borrow::Borrow;
-use std::path;
-use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use axum::Json;
 use axum::Router;
 use axum::body::Body;
+use axum::body::Bytes;
 use axum::extract;
+use axum::http::HeaderMap;
 use axum::http::StatusCode;
 use axum::http::header;
 use axum::response::IntoResponse;
 use axum::response::Response;
+use axum::routing::delete;
 use axum::routing::get;
+use axum::routing::put;
+use sha2::Digest;
+use sha2::Sha256;
 use tokio::task::spawn_blocking;
 use tower::limit::ConcurrencyLimitLayer;
 use tracing::error;
@@ -20,13 +25,18 @@ use crate::Config;
 use crate::Encoded;
 use crate::Encoding;
 use crate::Error;
+use crate::Frames;
 use crate::ResizeTo;
-use crate::load_resize_encode;
+use crate::cache::Cache;
+use crate::load_resize_encode_bytes;
+use crate::storage::Storage;
+use crate::transform_description;
 
 #[derive(Clone, Debug)]
 pub struct State {
     pub config: Arc<Config>,
-    pub images: Arc<PathBuf>,
+    pub storage: Arc<dyn Storage>,
+    pub cache: Option<Arc<Cache>>,
 }
 
 impl IntoResponse for Encoded {
@@ -50,12 +60,45 @@ impl FromStr for Encoding {
         let enc = match s {
             "avif" => Self::Avif,
             "jpeg" | "jpg" => Self::Jpeg,
+            "webp" => Self::WebP,
+            "png" => Self::Png,
             _ => return Err(()),
         };
         Ok(enc)
     }
 }
 
+/// Picks the best encoding the client advertises support for via `Accept`,
+/// preferring smaller formats first. Falls back to JPEG if nothing matches.
+fn negotiate_encoding(headers: &HeaderMap) -> Encoding {
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if accept.contains("image/avif") {
+        Encoding::Avif
+    } else if accept.contains("image/webp") {
+        Encoding::WebP
+    } else {
+        Encoding::Jpeg
+    }
+}
+
+fn resolve_encoding(encoding: &str, headers: &HeaderMap) -> Encoding {
+    if encoding == "auto" {
+        negotiate_encoding(headers)
+    } else {
+        encoding.parse().unwrap()
+    }
+}
+
+/// Per-request override of the server's default `--frames` setting, for
+/// video/animated-image inputs.
+#[derive(Debug, serde::Deserialize, Default)]
+struct ResizeQuery {
+    frames: Option<Frames>,
+}
+
 pub fn router(concurrency_limit: usize, state: State) -> Router {
     Router::new()
         .route(
@@ -74,6 +117,24 @@ pub fn router(concurrency_limit: usize, state: State) -> Router {
             "/images/{image}/size/scale/{scale}/encoding/{encoding}",
             get(h_s),
         )
+        .route(
+            "/images/sha256/{image}/size/{width}/{height}/encoding/{encoding}",
+            get(h_wh),
+        )
+        .route(
+            "/images/sha256/{image}/size/width/{width}/encoding/{encoding}",
+            get(h_w),
+        )
+        .route(
+            "/images/sha256/{image}/size/height/{width}/encoding/{encoding}",
+            get(h_h),
+        )
+        .route(
+            "/images/sha256/{image}/size/scale/{scale}/encoding/{encoding}",
+            get(h_s),
+        )
+        .route("/upload", put(h_upload))
+        .route("/{hash}", delete(h_delete))
         .layer(ConcurrencyLimitLayer::new(concurrency_limit))
         .with_state(state)
 }
@@ -86,6 +147,15 @@ impl IntoResponse for Error {
             Error::FailedToResize { message } => {
                 (StatusCode::INTERNAL_SERVER_ERROR, message).into_response()
             }
+            Error::HashMismatch { expected, actual } => {
+                let message = format!("sha256 mismatch: expected {expected}, got {actual}");
+                (StatusCode::BAD_REQUEST, message).into_response()
+            }
+            Error::InvalidKey { reason } => (StatusCode::BAD_REQUEST, reason).into_response(),
+            Error::Io { message } => (StatusCode::INTERNAL_SERVER_ERROR, message).into_response(),
+            Error::NotImplemented { message } => {
+                (StatusCode::NOT_IMPLEMENTED, message).into_response()
+            }
         }
     }
 }
@@ -93,13 +163,18 @@ impl IntoResponse for Error {
 async fn h_wh(
     extract::State(state): extract::State<State>,
     extract::Path((image, width, height, encoding)): extract::Path<(String, u32, u32, String)>,
+    extract::Query(query): extract::Query<ResizeQuery>,
+    headers: HeaderMap,
 ) -> Result<Encoded, Error> {
-    let image = state.images.join(&image);
+    let frames = query.frames.unwrap_or(state.config.frames);
     load_resize_encode_async(
         state.config,
+        state.cache,
+        state.storage,
         image,
-        encoding.parse().unwrap(),
+        resolve_encoding(&encoding, &headers),
         ResizeTo::WidthAndHeight(width, height),
+        frames,
     )
     .await
 }
@@ -107,13 +182,18 @@ async fn h_wh(
 async fn h_w(
     extract::State(state): extract::State<State>,
     extract::Path((image, width, encoding)): extract::Path<(String, u32, String)>,
+    extract::Query(query): extract::Query<ResizeQuery>,
+    headers: HeaderMap,
 ) -> Result<Encoded, Error> {
-    let image = state.images.join(&image);
+    let frames = query.frames.unwrap_or(state.config.frames);
     load_resize_encode_async(
         state.config,
+        state.cache,
+        state.storage,
         image,
-        encoding.parse().unwrap(),
+        resolve_encoding(&encoding, &headers),
         ResizeTo::Width(width),
+        frames,
     )
     .await
 }
@@ -121,13 +201,18 @@ async fn h_w(
 async fn h_h(
     extract::State(state): extract::State<State>,
     extract::Path((image, height, encoding)): extract::Path<(String, u32, String)>,
+    extract::Query(query): extract::Query<ResizeQuery>,
+    headers: HeaderMap,
 ) -> Result<Encoded, Error> {
-    let image = state.images.join(&image);
+    let frames = query.frames.unwrap_or(state.config.frames);
     load_resize_encode_async(
         state.config,
+        state.cache,
+        state.storage,
         image,
-        encoding.parse().unwrap(),
+        resolve_encoding(&encoding, &headers),
         ResizeTo::Height(height),
+        frames,
     )
     .await
 }
@@ -135,25 +220,134 @@ async fn h_h(
 async fn h_s(
     extract::State(state): extract::State<State>,
     extract::Path((image, scale, encoding)): extract::Path<(String, f64, String)>,
+    extract::Query(query): extract::Query<ResizeQuery>,
+    headers: HeaderMap,
 ) -> Result<Encoded, Error> {
-    let image = state.images.join(&image);
+    let frames = query.frames.unwrap_or(state.config.frames);
     load_resize_encode_async(
         state.config,
+        state.cache,
+        state.storage,
         image,
-        encoding.parse().unwrap(),
+        resolve_encoding(&encoding, &headers),
         ResizeTo::Scale(scale),
+        frames,
     )
     .await
 }
 
+/// Response body for a successful `PUT /upload`, describing the stored
+/// blob as in the Blossom BUD-05 spec.
+#[derive(Debug, serde::Serialize)]
+struct UploadResponse {
+    sha256: String,
+    size: u64,
+    #[serde(rename = "type")]
+    content_type: String,
+    /// Template for resizing this blob; `{width}`/`{height}`/`{encoding}`
+    /// are placeholders for the caller to fill in.
+    url: String,
+}
+
+#[derive(Debug, serde::Deserialize, Default)]
+struct UploadQuery {
+    sha256: Option<String>,
+}
+
+async fn h_upload(
+    extract::State(state): extract::State<State>,
+    extract::Query(query): extract::Query<UploadQuery>,
+    body: Bytes,
+) -> Result<Json<UploadResponse>, Error> {
+    let actual = format!("{:x}", Sha256::digest(&body));
+    if let Some(expected) = query.sha256 {
+        if expected != actual {
+            return Err(Error::HashMismatch { expected, actual });
+        }
+    }
+
+    // Validate that the body actually decodes as an image before storing it.
+    // `guess_image_format` fully decodes the image, so push it onto the
+    // blocking pool like every other decode path in this codebase.
+    let format = {
+        let body = body.clone();
+        spawn_blocking(move || crate::guess_image_format(&body))
+            .await
+            .unwrap()?
+    };
+
+    state.storage.put(&actual, body.clone()).await?;
+
+    Ok(Json(UploadResponse {
+        sha256: actual.clone(),
+        size: body.len() as u64,
+        content_type: format.to_mime_type().to_string(),
+        url: format!("/images/sha256/{actual}/size/{{width}}/{{height}}/encoding/{{encoding}}"),
+    }))
+}
+
+/// Blobs are addressed by their sha256 digest, so a valid key here is
+/// always exactly 64 lowercase hex characters. Rejecting anything else
+/// before it reaches `Storage` keeps path-traversal segments (`..`, `/`,
+/// an absolute-looking key) out of the delete path.
+fn is_sha256_hex(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+}
+
+async fn h_delete(
+    extract::State(state): extract::State<State>,
+    extract::Path(hash): extract::Path<String>,
+) -> Result<StatusCode, Error> {
+    if !is_sha256_hex(&hash) {
+        return Err(Error::InvalidKey {
+            reason: "hash must be a 64-character lowercase sha256 hex digest".to_string(),
+        });
+    }
+    state.storage.delete(&hash).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
 async fn load_resize_encode_async(
     config: impl Borrow<Config> + Send + 'static,
-    image: impl AsRef<path::Path> + Send + 'static,
+    cache: Option<Arc<Cache>>,
+    storage: Arc<dyn Storage>,
+    key: String,
     encoding: Encoding,
     to: ResizeTo,
+    frames: Frames,
 ) -> Result<Encoded, Error> {
-    let image = spawn_blocking(move || load_resize_encode(config, image.as_ref(), encoding, to))
-        .await
-        .unwrap()?;
-    Ok(image)
+    let transform = transform_description(config.borrow(), encoding, to, frames);
+
+    // Stat (not fetch) the source first, so a cache hit never touches the
+    // source bytes at all — a full disk read or S3 GetObject per request
+    // would defeat the point of caching the expensive encode.
+    let cache_key = if cache.is_some() {
+        let stat = storage.stat(&key).await?;
+        Some(Cache::key(&key, &stat, &transform))
+    } else {
+        None
+    };
+
+    if let (Some(cache), Some(cache_key)) = (&cache, &cache_key) {
+        if let Some(bytes) = cache.get(cache_key) {
+            return Ok(Encoded {
+                name: Some(key),
+                bytes,
+                encoding,
+            });
+        }
+    }
+
+    let bytes = storage.get(&key).await?;
+    let encoded = spawn_blocking(move || {
+        load_resize_encode_bytes(config, &key, bytes, encoding, to, frames)
+    })
+    .await
+    .unwrap()?;
+
+    if let (Some(cache), Some(cache_key)) = (&cache, &cache_key) {
+        cache.put(cache_key, &encoded.bytes);
+    }
+
+    Ok(encoded)
 }
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use bytes::Bytes;
+
+use crate::Error;
+
+/// Where source images are fetched from before decoding, and where
+/// uploaded blobs are stored.
+#[async_trait::async_trait]
+pub(crate) trait Storage: fmt::Debug + Send + Sync {
+    async fn get(&self, key: &str) -> Result<Bytes, Error>;
+    async fn put(&self, key: &str, bytes: Bytes) -> Result<(), Error>;
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+    /// A cheap, stat-only description of `key`'s current content (size and
+    /// modification time, or an object store's ETag) that changes whenever
+    /// the content does. Lets callers build a cache key without fetching
+    /// the full object.
+    async fn stat(&self, key: &str) -> Result<String, Error>;
+}
+
+/// Reads images from a directory on the local filesystem.
+#[derive(Debug)]
+pub(crate) struct Filesystem {
+    root: PathBuf,
+    io_uring: bool,
+}
+
+impl Filesystem {
+    pub(crate) fn new(root: PathBuf, io_uring: bool) -> Self {
+        Self { root, io_uring }
+    }
+
+    /// Joins `key` onto `root`, rejecting anything that could escape it:
+    /// empty keys, absolute-looking keys (which `PathBuf::join` would
+    /// otherwise treat as replacing `root` entirely), and `..` components.
+    fn resolve(&self, key: &str) -> Result<PathBuf, Error> {
+        if key.is_empty() || Path::new(key).is_absolute() {
+            return Err(Error::InvalidKey {
+                reason: "key must be a non-empty relative path".to_string(),
+            });
+        }
+        if Path::new(key)
+            .components()
+            .any(|component| matches!(component, std::path::Component::ParentDir))
+        {
+            return Err(Error::InvalidKey {
+                reason: "key must not contain `..` components".to_string(),
+            });
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for Filesystem {
+    async fn get(&self, key: &str) -> Result<Bytes, Error> {
+        let path = self.resolve(key)?;
+        crate::io_uring::read(&path, self.io_uring).await
+    }
+
+    async fn put(&self, key: &str, bytes: Bytes) -> Result<(), Error> {
+        let path = self.resolve(key)?;
+        tokio::fs::write(&path, bytes).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        let path = self.resolve(key)?;
+        tokio::fs::remove_file(&path).await?;
+        Ok(())
+    }
+
+    async fn stat(&self, key: &str) -> Result<String, Error> {
+        let path = self.resolve(key)?;
+        let metadata = tokio::fs::metadata(&path).await?;
+        let mtime_nanos = metadata
+            .modified()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or_default();
+        Ok(format!("{}-{mtime_nanos}", metadata.len()))
+    }
+}
+
+/// Where to reach an S3-compatible object store (MinIO, Garage, AWS S3).
+/// Credentials are picked up from the environment by the AWS SDK's default
+/// credential chain.
+#[derive(Debug, Clone)]
+pub(crate) struct S3Params {
+    pub(crate) endpoint: String,
+    pub(crate) bucket: String,
+    pub(crate) region: String,
+}
+
+/// Reads images out of an S3-compatible bucket.
+#[derive(Debug)]
+pub(crate) struct S3 {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+impl S3 {
+    pub(crate) async fn new(params: S3Params) -> Self {
+        let sdk_config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region(aws_sdk_s3::config::Region::new(params.region))
+            .endpoint_url(params.endpoint)
+            .load()
+            .await;
+        // MinIO and Garage (this backend's named targets) generally don't
+        // resolve virtual-hosted-style addressing (`<bucket>.<endpoint>`),
+        // so force path-style requests (`<endpoint>/<bucket>`) instead.
+        let config = aws_sdk_s3::config::Builder::from(&sdk_config)
+            .force_path_style(true)
+            .build();
+        let client = aws_sdk_s3::Client::from_conf(config);
+        Self {
+            client,
+            bucket: params.bucket,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for S3 {
+    async fn get(&self, key: &str) -> Result<Bytes, Error> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|error| Error::FailedToResize {
+                message: error.to_string(),
+            })?;
+        let bytes = output
+            .body
+            .collect()
+            .await
+            .map_err(|error| Error::FailedToResize {
+                message: error.to_string(),
+            })?
+            .into_bytes();
+        Ok(bytes)
+    }
+
+    async fn put(&self, key: &str, bytes: Bytes) -> Result<(), Error> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(bytes.into())
+            .send()
+            .await
+            .map_err(|error| Error::FailedToResize {
+                message: error.to_string(),
+            })?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|error| Error::FailedToResize {
+                message: error.to_string(),
+            })?;
+        Ok(())
+    }
+
+    async fn stat(&self, key: &str) -> Result<String, Error> {
+        let output = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|error| Error::FailedToResize {
+                message: error.to_string(),
+            })?;
+        // Prefer the ETag (changes whenever the object's content does); fall
+        // back to size+mtime for backends that don't return one.
+        let stat = output.e_tag().map_or_else(
+            || {
+                format!(
+                    "{}-{:?}",
+                    output.content_length().unwrap_or_default(),
+                    output.last_modified()
+                )
+            },
+            |etag| etag.trim_matches('"').to_string(),
+        );
+        Ok(stat)
+    }
+}
@@ -0,0 +1,113 @@
+//! ffmpeg-backed decoding for video and animated-image inputs (mp4, webm,
+//! gif). Gated behind the `ffmpeg` Cargo feature so builds without the
+//! native ffmpeg libraries still work; without the feature, [`thumbnail`]
+//! returns an [`Error`] explaining what's missing.
+
+use std::path::Path;
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "webm", "gif"];
+
+/// Whether `path`'s extension marks it as a video/animated-image input
+/// that should go through the ffmpeg path rather than the `image` crate.
+pub(crate) fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Same check as [`is_video`], but for a bare name/key rather than a path
+/// on disk (e.g. a storage key that may not correspond to a local file).
+pub(crate) fn is_video_name(name: &str) -> bool {
+    is_video(Path::new(name))
+}
+
+#[cfg(feature = "ffmpeg")]
+mod backend {
+    use std::path::Path;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use image::RgbaImage;
+    use tracing::debug;
+    use tracing::instrument;
+
+    use crate::Error;
+
+    fn ffmpeg_error(error: impl std::fmt::Display) -> Error {
+        Error::FailedToResize {
+            message: error.to_string(),
+        }
+    }
+
+    /// Seeks to the keyframe at or before `at` in the video/animation at
+    /// `path` and returns the first frame decoded from there, as RGBA8 and
+    /// ready for the existing resize/encode pipeline. The exact frame at
+    /// `at` is not searched for, and a failed seek is ignored, so decoding
+    /// then starts from the beginning.
+    #[instrument(skip_all)]
+    pub(crate) fn thumbnail(path: &Path, at: Duration) -> Result<RgbaImage, Error> {
+        let begin = Instant::now();
+        ffmpeg_next::init().map_err(ffmpeg_error)?;
+
+        let mut input = ffmpeg_next::format::input(path).map_err(ffmpeg_error)?;
+        let position = (at.as_secs_f64() * f64::from(ffmpeg_next::ffi::AV_TIME_BASE)).round() as i64;
+        let _ = input.seek(position, ..position);
+
+        let stream = input
+            .streams()
+            .best(ffmpeg_next::media::Type::Video)
+            .ok_or_else(|| ffmpeg_error("no video stream in input"))?;
+        let stream_index = stream.index();
+        let context = ffmpeg_next::codec::context::Context::from_parameters(stream.parameters())
+            .map_err(ffmpeg_error)?;
+        let mut decoder = context.decoder().video().map_err(ffmpeg_error)?;
+        let mut scaler = ffmpeg_next::software::scaling::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            ffmpeg_next::format::Pixel::RGBA,
+            decoder.width(),
+            decoder.height(),
+            ffmpeg_next::software::scaling::Flags::BILINEAR,
+        )
+        .map_err(ffmpeg_error)?;
+
+        for (packet_stream, packet) in input.packets() {
+            if packet_stream.index() != stream_index {
+                continue;
+            }
+            decoder.send_packet(&packet).map_err(ffmpeg_error)?;
+            let mut decoded = ffmpeg_next::frame::Video::empty();
+            if decoder.receive_frame(&mut decoded).is_ok() {
+                let mut rgba = ffmpeg_next::frame::Video::empty();
+                scaler.run(&decoded, &mut rgba).map_err(ffmpeg_error)?;
+                let image = RgbaImage::from_raw(rgba.width(), rgba.height(), rgba.data(0).to_vec())
+                    .ok_or_else(|| ffmpeg_error("decoded frame had an unexpected buffer size"))?;
+                debug!(
+                    elapsed_secs = begin.elapsed().as_secs_f64(),
+                    "extracted thumbnail frame"
+                );
+                return Ok(image);
+            }
+        }
+        Err(ffmpeg_error("no decodable frame found"))
+    }
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+mod backend {
+    use std::path::Path;
+    use std::time::Duration;
+
+    use image::RgbaImage;
+
+    use crate::Error;
+
+    pub(crate) fn thumbnail(_path: &Path, _at: Duration) -> Result<RgbaImage, Error> {
+        Err(Error::FailedToResize {
+            message: "video input requires building with the `ffmpeg` feature".to_string(),
+        })
+    }
+}
+
+pub(crate) use backend::thumbnail;